@@ -0,0 +1,43 @@
+//! Chip-related functionality.
+
+use anyhow::{bail, Result};
+
+/// Xtensa and RISC-V chips supported by the Espressif Rust ecosystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+    Esp32,
+    Esp32S2,
+    Esp32S3,
+    Esp32C3,
+}
+
+impl Chip {
+    /// Whether the chip uses the Xtensa architecture.
+    pub fn xtensa(&self) -> bool {
+        matches!(self, Chip::Esp32 | Chip::Esp32S2 | Chip::Esp32S3)
+    }
+
+    /// Whether the chip uses the RISC-V architecture.
+    pub fn riscv(&self) -> bool {
+        matches!(self, Chip::Esp32C3)
+    }
+
+    /// Parses a comma/space separated list of target names, or `"all"` for
+    /// every supported chip.
+    pub fn parse_targets(targets: &str) -> Result<Vec<Chip>> {
+        if targets.trim() == "all" {
+            return Ok(vec![Chip::Esp32, Chip::Esp32S2, Chip::Esp32S3, Chip::Esp32C3]);
+        }
+        targets
+            .split([',', ' '])
+            .filter(|s| !s.is_empty())
+            .map(|s| match s {
+                "esp32" => Ok(Chip::Esp32),
+                "esp32s2" => Ok(Chip::Esp32S2),
+                "esp32s3" => Ok(Chip::Esp32S3),
+                "esp32c3" => Ok(Chip::Esp32C3),
+                other => bail!("Unknown target: '{}'", other),
+            })
+            .collect()
+    }
+}
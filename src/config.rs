@@ -0,0 +1,55 @@
+//! TOML configuration file support, letting a checked-in `espup.toml` drive
+//! `espup install` non-interactively.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default location checked for a config file when `--config` isn't given.
+const DEFAULT_CONFIG_FILE: &str = "espup.toml";
+
+/// Mirrors the subset of install settings that make sense to pin in a
+/// checked-in file, so that a team can reproduce an exact ESP toolchain set
+/// across machines. Every field is optional: whatever is missing here falls
+/// back to the CLI value (if given) and then to the built-in default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub toolchain_version: Option<String>,
+    pub extra_crates: Option<String>,
+    pub nightly_version: Option<String>,
+    pub cargo_home: Option<PathBuf>,
+    pub rustup_home: Option<PathBuf>,
+    pub toolchain_destination: Option<PathBuf>,
+    pub targets: Option<String>,
+    pub llvm_minified: Option<bool>,
+    pub llvm_version: Option<String>,
+    pub toolchain_repo: Option<String>,
+    pub llvm_repo: Option<String>,
+    pub concurrency: Option<usize>,
+}
+
+impl Config {
+    /// Loads the config from `path`, or from [`DEFAULT_CONFIG_FILE`] in the
+    /// current directory if `path` is `None` and that file exists. Returns
+    /// an empty (all-`None`) config if neither is present, since a config
+    /// file is always optional.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => {
+                let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+                default.exists().then_some(default)
+            }
+        };
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file '{}'", path.display()))
+    }
+}
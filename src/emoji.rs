@@ -0,0 +1,7 @@
+//! Emojis used throughout the crate's log output.
+
+pub const DOWNLOAD: &str = "📥";
+pub const ERROR: &str = "❌";
+pub const INFO: &str = "💡";
+pub const WARN: &str = "⚠️";
+pub const WRENCH: &str = "🔧";
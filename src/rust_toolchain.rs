@@ -3,7 +3,7 @@
 use super::InstallOpts;
 use crate::chip::Chip;
 use crate::emoji;
-use crate::utils::{download_file, get_dist_path};
+use crate::utils::{download_file_with_checksum, fetch_companion_checksum, get_dist_path, run_concurrent};
 use anyhow::Result;
 use embuild::cmd;
 use log::info;
@@ -35,6 +35,8 @@ pub struct RustToolchain {
     pub toolchain_destination: PathBuf,
     /// Xtensa Rust Toolchain version.
     pub version: String,
+    /// Number of downloads/extractions to run concurrently.
+    pub concurrency: usize,
 }
 
 impl RustToolchain {
@@ -78,26 +80,91 @@ impl RustToolchain {
         Ok(())
     }
 
-    /// Installs the Xtensa Rust toolchain.
-    pub fn install_xtensa(&self) -> Result<()> {
+    /// Whether `toolchain_destination` already contains a valid Xtensa Rust
+    /// install, probed by checking for the `rustc` binary and the
+    /// `rust-src` component that `install.sh` lays down.
+    fn is_installed(&self) -> bool {
         let host_triple = guess_host_triple::guess_host_triple().unwrap();
+        let rustc = if host_triple.contains("windows") {
+            "bin/rustc.exe"
+        } else {
+            "bin/rustc"
+        };
+        self.toolchain_destination.join(rustc).is_file()
+            && self
+                .toolchain_destination
+                .join("lib/rustlib/src/rust")
+                .is_dir()
+    }
+
+    /// Installs the Xtensa Rust toolchain. If `force` is set, any existing
+    /// installation at `toolchain_destination` is removed first; otherwise
+    /// a valid existing installation is left untouched and the
+    /// download/extraction/install-script steps are skipped.
+    pub fn install_xtensa(&self, force: bool) -> Result<()> {
+        let host_triple = guess_host_triple::guess_host_triple().unwrap();
+
+        if force && self.toolchain_destination.exists() {
+            info!(
+                "{} Removing previous Xtensa Rust installation in: {}",
+                emoji::WRENCH,
+                self.toolchain_destination.display()
+            );
+            std::fs::remove_dir_all(&self.toolchain_destination)?;
+        }
+
+        if self.is_installed() {
+            info!(
+                "{} Xtensa Rust toolchain already installed in: {}, skipping download",
+                emoji::INFO,
+                self.toolchain_destination.display()
+            );
+            return Ok(());
+        }
 
         // Some platfroms like Windows are available in single bundle rust + src, because install
         // script in dist is not available for the plaform. It's sufficient to extract the toolchain
         if Self::get_installer(host_triple).to_string().is_empty() {
-            download_file(
+            let checksum = fetch_companion_checksum(&self.dist_url);
+            download_file_with_checksum(
                 self.dist_url.clone(),
                 "rust.zip",
                 &self.toolchain_destination.display().to_string(),
                 true,
+                checksum.as_deref(),
             )?;
         } else {
-            download_file(
-                self.dist_url.clone(),
-                "rust.tar.xz",
-                &get_dist_path("rust"),
-                true,
+            // The dist and src archives are independent downloads, so they're
+            // fetched and extracted concurrently. Their install.sh scripts
+            // are still run one after the other: both write into the same
+            // `toolchain_destination`, and rust-installer keeps a shared
+            // component manifest/uninstall script there that isn't safe to
+            // update from two processes at once.
+            let download_rust = || -> Result<()> {
+                let checksum = fetch_companion_checksum(&self.dist_url);
+                download_file_with_checksum(
+                    self.dist_url.clone(),
+                    "rust.tar.xz",
+                    &get_dist_path("rust"),
+                    true,
+                    checksum.as_deref(),
+                )
+            };
+            let download_rust_src = || -> Result<()> {
+                let checksum = fetch_companion_checksum(&self.src_dist_url);
+                download_file_with_checksum(
+                    self.src_dist_url.clone(),
+                    "rust-src.tar.xz",
+                    &get_dist_path("rust-src"),
+                    true,
+                    checksum.as_deref(),
+                )
+            };
+            run_concurrent(
+                vec![Box::new(download_rust), Box::new(download_rust_src)],
+                self.concurrency,
             )?;
+
             info!("{} Installing rust esp toolchain", emoji::WRENCH);
             let arguments = format!(
                 "{}/rust-nightly-{}/install.sh --destdir={} --prefix='' --without=rust-docs",
@@ -107,12 +174,6 @@ impl RustToolchain {
             );
             cmd!("/bin/bash", "-c", arguments).run()?;
 
-            download_file(
-                self.src_dist_url.clone(),
-                "rust-src.tar.xz",
-                &get_dist_path("rust-src"),
-                true,
-            )?;
             info!("{} Installing rust-src for esp toolchain", emoji::WRENCH);
             let arguments = format!(
                 "{}/rust-src-nightly/install.sh --destdir={} --prefix='' --without=rust-docs",
@@ -129,19 +190,17 @@ impl RustToolchain {
     pub fn new(args: &InstallOpts, arch: &str, targets: &[Chip]) -> Self {
         let artifact_extension = Self::get_artifact_extension(arch);
         let version = args.toolchain_version.clone();
+        let repository = args
+            .toolchain_repo
+            .as_deref()
+            .unwrap_or(DEFAULT_XTENSA_RUST_REPOSITORY);
 
         let dist = format!("rust-{}-{}", args.toolchain_version, arch);
         let dist_file = format!("{}.{}", dist, artifact_extension);
-        let dist_url = format!(
-            "{}/v{}/{}",
-            DEFAULT_XTENSA_RUST_REPOSITORY, version, dist_file
-        );
+        let dist_url = format!("{}/v{}/{}", repository, version, dist_file);
         let src_dist = format!("rust-src-{}", args.toolchain_version);
         let src_dist_file = format!("{}.{}", src_dist, artifact_extension);
-        let src_dist_url = format!(
-            "{}/v{}/{}",
-            DEFAULT_XTENSA_RUST_REPOSITORY, version, src_dist_file
-        );
+        let src_dist_url = format!("{}/v{}/{}", repository, version, src_dist_file);
 
         Self {
             dist_file,
@@ -155,6 +214,69 @@ impl RustToolchain {
             rustup_home: args.rustup_home.clone(),
             toolchain_destination: args.toolchain_destination.clone(),
             version,
+            concurrency: args.concurrency,
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install_opts(toolchain_repo: Option<&str>) -> InstallOpts {
+        InstallOpts {
+            export_file: PathBuf::from("export.sh"),
+            extra_crates: "cargo-espflash".to_string(),
+            nightly_version: "nightly".to_string(),
+            cargo_home: PathBuf::from("/home/user/.cargo"),
+            rustup_home: PathBuf::from("/home/user/.rustup"),
+            toolchain_version: "1.70.0.0".to_string(),
+            toolchain_destination: PathBuf::from("/home/user/.espup/esp-rust"),
+            targets: "all".to_string(),
+            llvm_minified: false,
+            llvm_version: None,
+            toolchain_repo: toolchain_repo.map(str::to_string),
+            llvm_repo: None,
+            force: false,
+            concurrency: 1,
+        }
+    }
+
+    #[test]
+    fn test_new_uses_default_repository() {
+        let args = install_opts(None);
+        let toolchain = RustToolchain::new(&args, "x86_64-unknown-linux-gnu", &[]);
+        assert_eq!(
+            toolchain.dist_url,
+            "https://github.com/esp-rs/rust-build/releases/download/v1.70.0.0/rust-1.70.0.0-x86_64-unknown-linux-gnu.tar.xz"
+        );
+        assert_eq!(
+            toolchain.src_dist_url,
+            "https://github.com/esp-rs/rust-build/releases/download/v1.70.0.0/rust-src-1.70.0.0.tar.xz"
+        );
+    }
+
+    #[test]
+    fn test_new_honors_toolchain_repo_override() {
+        let args = install_opts(Some("https://mirror.internal/rust-build"));
+        let toolchain = RustToolchain::new(&args, "x86_64-unknown-linux-gnu", &[]);
+        assert_eq!(
+            toolchain.dist_url,
+            "https://mirror.internal/rust-build/v1.70.0.0/rust-1.70.0.0-x86_64-unknown-linux-gnu.tar.xz"
+        );
+        assert_eq!(
+            toolchain.src_dist_url,
+            "https://mirror.internal/rust-build/v1.70.0.0/rust-src-1.70.0.0.tar.xz"
+        );
+    }
+
+    #[test]
+    fn test_new_picks_zip_extension_on_windows() {
+        let args = install_opts(Some("https://mirror.internal/rust-build"));
+        let toolchain = RustToolchain::new(&args, "x86_64-pc-windows-msvc", &[]);
+        assert_eq!(
+            toolchain.dist_url,
+            "https://mirror.internal/rust-build/v1.70.0.0/rust-1.70.0.0-x86_64-pc-windows-msvc.zip"
+        );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,12 @@
+//! esp-idf tools directory helpers.
+
+/// Gets the path where a given esp-idf tool is (or will be) installed.
+pub fn get_tool_path(tool_name: &str) -> String {
+    let data_dir = dirs::data_local_dir().unwrap();
+    data_dir
+        .join("espup")
+        .join("tools")
+        .join(tool_name)
+        .display()
+        .to_string()
+}
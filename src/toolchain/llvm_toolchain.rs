@@ -2,7 +2,8 @@
 
 use crate::{
     emoji,
-    toolchain::{download_file, espidf::get_tool_path},
+    toolchain::espidf::get_tool_path,
+    utils::{download_file_with_checksum, fetch_companion_checksum},
 };
 use anyhow::{bail, Ok, Result};
 use log::info;
@@ -10,8 +11,8 @@ use std::path::{Path, PathBuf};
 
 const DEFAULT_LLVM_COMPLETE_REPOSITORY: &str =
     "https://github.com/espressif/llvm-project/releases/download";
-const DEFAULT_LLVM_MINIFIED_REPOSITORY: &str =
-    "https://github.com/esp-rs/rust-build/releases/download/llvm-project-14.0-minified";
+const DEFAULT_LLVM_MINIFIED_REPOSITORY_BASE: &str =
+    "https://github.com/esp-rs/rust-build/releases/download";
 const DEFAULT_LLVM_VERSION: &str = "esp-14.0.0-20220415";
 
 #[derive(Debug)]
@@ -58,19 +59,46 @@ impl LlvmToolchain {
         llvm_path
     }
 
-    /// Installs the LLVM toolchain.
-    pub fn install(&self) -> Result<Vec<String>> {
+    /// Whether `self.path` already contains a valid LLVM install, probed by
+    /// checking for the `xtensa-esp32-elf-clang/{bin,lib}` directories that
+    /// the installed archive always produces.
+    fn is_installed(&self) -> bool {
+        let clang_dir = self.path.join("xtensa-esp32-elf-clang");
+        clang_dir.join("bin").is_dir() && clang_dir.join("lib").is_dir()
+    }
+
+    /// Installs the LLVM toolchain. If `force` is set, any existing
+    /// installation at `self.path` is removed first; otherwise a valid
+    /// existing installation is left untouched and the download/extraction
+    /// is skipped.
+    pub fn install(&self, force: bool) -> Result<Vec<String>> {
         let mut exports: Vec<String> = Vec::new();
 
-        if Path::new(&self.path).exists() {
+        if force && Path::new(&self.path).exists() {
+            info!(
+                "{} Removing previous LLVM installation in: {}",
+                emoji::WRENCH,
+                self.path.display()
+            );
+            std::fs::remove_dir_all(&self.path)?;
+        }
+
+        if self.is_installed() {
+            info!(
+                "{} LLVM already installed in: {}, skipping download",
+                emoji::INFO,
+                self.path.display()
+            );
+        } else if Path::new(&self.path).exists() {
             bail!(
-            "{} Previous installation of LLVM exist in: {}.\n Please, remove the directory before new installation.",
+            "{} Previous installation of LLVM exist in: {}.\n Please, remove the directory before new installation, or pass --force.",
             emoji::WARN,
             self.path.to_str().unwrap()
         );
         } else {
             info!("{} Installing Xtensa elf Clang", emoji::WRENCH);
-            download_file(
+            let checksum = fetch_companion_checksum(&self.repository_url);
+            download_file_with_checksum(
                 self.repository_url.clone(),
                 &format!(
                     "idf_tool_xtensa_elf_clang.{}",
@@ -78,6 +106,7 @@ impl LlvmToolchain {
                 ),
                 self.path.to_str().unwrap(),
                 true,
+                checksum.as_deref(),
             )?;
         }
         // Set environment variables.
@@ -95,9 +124,22 @@ impl LlvmToolchain {
     }
 
     /// Create a new instance with default values and proper toolchain version.
-    pub fn new(minified: bool) -> Self {
+    ///
+    /// `repo_override` replaces the default GitHub repository (either the
+    /// minified or the complete one, depending on `minified`), letting
+    /// air-gapped or mirrored installs point at an internal host while
+    /// preserving the upstream asset tree layout. When there's no override,
+    /// the default minified repository's release tag tracks `version`'s
+    /// `major.minor` (see [`major_minor_release`]), so selecting a non-default
+    /// version doesn't leave the minified download pointed at the wrong
+    /// release. `version` picks an esp-clang release other than
+    /// [`DEFAULT_LLVM_VERSION`]; it must match the
+    /// `esp-<major.minor.patch>-<date>` shape used by espressif/llvm-project
+    /// releases, or this returns an error before any download is attempted.
+    pub fn new(minified: bool, repo_override: Option<&str>, version: Option<&str>) -> Result<Self> {
         let host_triple = guess_host_triple::guess_host_triple().unwrap();
-        let version = DEFAULT_LLVM_VERSION.to_string();
+        let version = version.unwrap_or(DEFAULT_LLVM_VERSION).to_string();
+        validate_version(&version)?;
         let file_name: String;
         let repository_url: String;
         if minified {
@@ -108,7 +150,13 @@ impl LlvmToolchain {
                 host_triple,
                 Self::get_artifact_extension(host_triple)
             );
-            repository_url = format!("{}/{}", DEFAULT_LLVM_MINIFIED_REPOSITORY, file_name,);
+            let default_repository = format!(
+                "{}/llvm-project-{}-minified",
+                DEFAULT_LLVM_MINIFIED_REPOSITORY_BASE,
+                major_minor_release(&version)
+            );
+            let repository = repo_override.unwrap_or(&default_repository);
+            repository_url = format!("{repository}/{file_name}");
         } else {
             file_name = format!(
                 "xtensa-esp32-elf-llvm{}-{}-{}.{}",
@@ -117,10 +165,8 @@ impl LlvmToolchain {
                 Self::get_arch(host_triple).unwrap(),
                 Self::get_artifact_extension(host_triple)
             );
-            repository_url = format!(
-                "{}/{}/{}",
-                DEFAULT_LLVM_COMPLETE_REPOSITORY, &version, file_name
-            );
+            let repository = repo_override.unwrap_or(DEFAULT_LLVM_COMPLETE_REPOSITORY);
+            repository_url = format!("{repository}/{version}/{file_name}");
         }
         let path = format!(
             "{}/{}-{}",
@@ -129,13 +175,34 @@ impl LlvmToolchain {
             host_triple
         )
         .into();
-        Self {
+        Ok(Self {
             repository_url,
             version,
             file_name,
             path,
-        }
+        })
+    }
+}
+
+/// Checks that `version` matches the `esp-<major.minor.patch>-<date>` shape
+/// used by espressif/llvm-project releases (e.g. `esp-14.0.0-20220415`),
+/// `bail!`-ing with a clear message rather than letting a malformed version
+/// 404 partway through the download.
+fn validate_version(version: &str) -> Result<()> {
+    let parts: Vec<&str> = version.split('-').collect();
+    let is_valid = matches!(parts.as_slice(), ["esp", release, date]
+        if release.split('.').count() == 3
+            && release.split('.').all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+            && date.len() == 8
+            && date.chars().all(|c| c.is_ascii_digit()));
+    if !is_valid {
+        bail!(
+            "{} Invalid LLVM version '{}': expected the 'esp-<major.minor.patch>-<date>' format, e.g. 'esp-14.0.0-20220415'",
+            emoji::ERROR,
+            version
+        );
     }
+    Ok(())
 }
 
 /// Gets the parsed version name.
@@ -145,9 +212,20 @@ fn get_release_with_underscores(version: &str) -> String {
     llvm_dot_release.replace('.', "_")
 }
 
+/// Gets the `<major>.<minor>` prefix of a version's `major.minor.patch`
+/// release component (e.g. `"14.0"` from `"esp-14.0.0-20220415"`), matching
+/// the tag scheme esp-rs/rust-build uses for its minified LLVM releases
+/// (e.g. `llvm-project-14.0-minified`).
+fn major_minor_release(version: &str) -> String {
+    let release = version.split('-').collect::<Vec<&str>>()[1];
+    release.rsplit_once('.').map_or(release, |(major_minor, _)| major_minor).to_string()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::toolchain::llvm_toolchain::get_release_with_underscores;
+    use crate::toolchain::llvm_toolchain::{
+        get_release_with_underscores, major_minor_release, validate_version,
+    };
 
     #[test]
     fn test_get_release_with_underscores() {
@@ -156,4 +234,64 @@ mod tests {
             "14_0_0".to_string()
         );
     }
+
+    #[test]
+    fn test_major_minor_release() {
+        assert_eq!(major_minor_release("esp-14.0.0-20220415"), "14.0");
+        assert_eq!(major_minor_release("esp-15.0.1-20230516"), "15.0");
+    }
+
+    #[test]
+    fn test_validate_version() {
+        assert!(validate_version("esp-14.0.0-20220415").is_ok());
+        assert!(validate_version("14.0.0-20220415").is_err());
+        assert!(validate_version("esp-14.0-20220415").is_err());
+        assert!(validate_version("esp-14.0.0-2022415").is_err());
+        assert!(validate_version("esp-14.x.0-20220415").is_err());
+    }
+
+    #[test]
+    fn test_new_uses_default_repository_when_no_override() {
+        let toolchain = super::LlvmToolchain::new(false, None, None).unwrap();
+        assert!(toolchain
+            .repository_url
+            .starts_with(super::DEFAULT_LLVM_COMPLETE_REPOSITORY));
+        assert!(toolchain.repository_url.ends_with(&toolchain.file_name));
+    }
+
+    #[test]
+    fn test_new_honors_repo_override_complete() {
+        let toolchain =
+            super::LlvmToolchain::new(false, Some("https://mirror.internal/llvm"), None).unwrap();
+        assert_eq!(
+            toolchain.repository_url,
+            format!(
+                "https://mirror.internal/llvm/{}/{}",
+                toolchain.version, toolchain.file_name
+            )
+        );
+    }
+
+    #[test]
+    fn test_new_honors_repo_override_minified() {
+        let toolchain =
+            super::LlvmToolchain::new(true, Some("https://mirror.internal/llvm"), None).unwrap();
+        assert_eq!(
+            toolchain.repository_url,
+            format!("https://mirror.internal/llvm/{}", toolchain.file_name)
+        );
+    }
+
+    #[test]
+    fn test_new_minified_default_repository_tracks_version() {
+        let toolchain =
+            super::LlvmToolchain::new(true, None, Some("esp-15.0.0-20230516")).unwrap();
+        assert_eq!(
+            toolchain.repository_url,
+            format!(
+                "https://github.com/esp-rs/rust-build/releases/download/llvm-project-15.0-minified/{}",
+                toolchain.file_name
+            )
+        );
+    }
 }
\ No newline at end of file
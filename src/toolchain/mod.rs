@@ -0,0 +1,4 @@
+//! Toolchain installation and management.
+
+pub mod espidf;
+pub mod llvm_toolchain;
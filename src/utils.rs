@@ -0,0 +1,557 @@
+//! Generic utilities used when installing and configuring toolchains.
+
+use crate::emoji;
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Number of attempts a transfer is retried before giving up.
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+/// Base delay used for the exponential backoff between retries.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Size of the buffer used to stream the response body to disk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Downloads a file from `url` into `output_directory/file_name`, resuming a
+/// previously interrupted transfer and retrying transient failures with
+/// exponential backoff, and verifies the downloaded bytes against
+/// `expected_sha256` (a hex-encoded SHA-256 digest) before extraction,
+/// `bail!`-ing if they don't match. If `uncompress` is true, the downloaded
+/// archive is extracted into `output_directory` and the archive itself is
+/// removed.
+///
+/// Does not yet report transfer progress to the caller; a byte-progress
+/// callback was prototyped and then dropped (see history) because nothing
+/// in the crate renders a progress bar. Re-add it once there's an actual
+/// caller instead of threading an unused parameter through again.
+pub fn download_file_with_checksum(
+    url: String,
+    file_name: &str,
+    output_directory: &str,
+    uncompress: bool,
+    expected_sha256: Option<&str>,
+) -> Result<()> {
+    fs::create_dir_all(output_directory)?;
+    let partial_path = Path::new(output_directory).join(format!("{file_name}.partial"));
+    let final_path = Path::new(output_directory).join(file_name);
+
+    if final_path.exists() {
+        debug!(
+            "{} File '{}' already exists, skipping download",
+            emoji::INFO,
+            final_path.display()
+        );
+    } else {
+        info!("{} Downloading '{}'", emoji::DOWNLOAD, file_name);
+        let client = Client::new();
+        let mut hasher = Sha256::new();
+        // How many bytes of the on-disk `.partial` file are reflected in
+        // `hasher` so far; see `stream_to_partial`. Tracking the actual byte
+        // count, rather than just whether a rehash has happened, means a
+        // retry whose disk state doesn't match what `hasher` expects (e.g. a
+        // short write landed on disk just before an I/O error) is detected
+        // and re-synced instead of silently trusted.
+        let mut hashed_len: u64 = 0;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match try_download(&client, &url, &partial_path, &mut hasher, &mut hashed_len) {
+                Ok(()) => break,
+                Err(err) if attempt < MAX_DOWNLOAD_ATTEMPTS => {
+                    warn!(
+                        "{} Download of '{}' failed (attempt {}/{}): {}. Retrying...",
+                        emoji::WARN,
+                        file_name,
+                        attempt,
+                        MAX_DOWNLOAD_ATTEMPTS,
+                        err
+                    );
+                    thread::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1));
+                    // The partial file on disk carries the bytes written so
+                    // far; a fresh attempt resumes from it rather than
+                    // starting over, rehashing that prefix only if `hasher`
+                    // no longer matches it (see `stream_to_partial`).
+                }
+                Err(err) => {
+                    bail!(
+                        "{} Failed to download '{}' after {} attempts: {}",
+                        emoji::ERROR,
+                        file_name,
+                        MAX_DOWNLOAD_ATTEMPTS,
+                        err
+                    );
+                }
+            }
+        }
+
+        if let Some(expected) = expected_sha256 {
+            let digest = hex::encode(hasher.finalize());
+            if !digest.eq_ignore_ascii_case(expected) {
+                // The partial file reflects the exact bytes that just failed
+                // to verify; leaving it in place would make the next
+                // invocation resume from those same corrupt bytes and fail
+                // the same way forever.
+                fs::remove_file(&partial_path).ok();
+                bail!(
+                    "{} Checksum mismatch for '{}': expected {}, got {}",
+                    emoji::ERROR,
+                    file_name,
+                    expected,
+                    digest
+                );
+            }
+        }
+
+        fs::rename(&partial_path, &final_path).with_context(|| {
+            format!(
+                "{} Failed to move downloaded file '{}' into place",
+                emoji::ERROR,
+                final_path.display()
+            )
+        })?;
+    }
+
+    if uncompress {
+        uncompress_file(&final_path, output_directory)?;
+        fs::remove_file(&final_path)?;
+    }
+
+    Ok(())
+}
+
+/// Performs a single download attempt, resuming from the size of any
+/// existing partial file. `url` may be an `http(s)://` URL, fetched with an
+/// HTTP `Range` request (falling back to restarting from zero if the server
+/// doesn't honor it, i.e. it replies `200 OK` instead of `206 Partial
+/// Content`, or discarding the partial and restarting if it replies `416
+/// Range Not Satisfiable`, which happens when a previous run wrote the last
+/// byte but was killed before renaming the file into place), or a `file://`
+/// URL, read directly from disk for offline/air-gapped mirrors.
+/// `hashed_len` tracks, across retries of the same
+/// `download_file_with_checksum` call, how many bytes of the `.partial`
+/// file are already folded into `hasher`; see [`stream_to_partial`]. Only
+/// renames/completes once the full content length (when known) has
+/// actually been transferred.
+fn try_download(
+    client: &Client,
+    url: &str,
+    partial_path: &Path,
+    hasher: &mut Sha256,
+    hashed_len: &mut u64,
+) -> Result<()> {
+    let resume_from = fs::metadata(partial_path).map(|m| m.len()).unwrap_or(0);
+
+    let (total_size, downloaded) = if let Some(path) = file_url_path(url) {
+        let mut source = File::open(&path).with_context(|| {
+            format!(
+                "{} Failed to open local file '{}'",
+                emoji::ERROR,
+                path.display()
+            )
+        })?;
+        let total_size = source.metadata()?.len();
+        let resuming = resume_from > 0 && resume_from <= total_size;
+        if resuming {
+            source.seek(SeekFrom::Start(resume_from))?;
+        }
+        let file = open_partial(partial_path, resuming)?;
+        let downloaded = stream_to_partial(
+            source,
+            file,
+            partial_path,
+            hasher,
+            resuming,
+            hashed_len,
+            resume_from,
+            Some(total_size),
+        )?;
+        (Some(total_size), downloaded)
+    } else {
+        let mut request = client.get(url);
+        if resume_from > 0 {
+            request = request.header(RANGE, format!("bytes={resume_from}-"));
+        }
+        let response = request.send()?;
+        if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            // The server disagrees with the size of our partial file, most
+            // likely because a previous run already wrote the whole thing
+            // but was killed before `download_file_with_checksum` could
+            // rename it into place. Discard it and let the caller retry
+            // from scratch rather than requesting the same impossible range
+            // forever.
+            fs::remove_file(partial_path).ok();
+            bail!(
+                "{} Range not satisfiable for '{}', discarding partial file and restarting",
+                emoji::WARN,
+                url
+            );
+        }
+        let response = response.error_for_status()?;
+
+        let resuming = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if !resuming && resume_from > 0 {
+            // We asked for a range but the server doesn't support resuming:
+            // start the partial file over from scratch.
+            debug!(
+                "{} Server returned {} instead of 206, restarting download from zero",
+                emoji::INFO,
+                response.status()
+            );
+        }
+
+        let total_size = response_total_size(response.headers(), resuming, resume_from);
+        let file = open_partial(partial_path, resuming)?;
+        let downloaded = stream_to_partial(
+            response,
+            file,
+            partial_path,
+            hasher,
+            resuming,
+            hashed_len,
+            resume_from,
+            total_size,
+        )?;
+        (total_size, downloaded)
+    };
+
+    if let Some(total) = total_size {
+        if downloaded != total {
+            bail!(
+                "{} Transfer ended early after {} of {} expected bytes",
+                emoji::ERROR,
+                downloaded,
+                total
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the *full* file size from a response's headers, whether or not it's
+/// a `206` partial response. On `206`, `Content-Length` is only the size of
+/// the remaining range, not the whole file, so the total is read from
+/// `Content-Range: bytes <start>-<end>/<total>` when present, or otherwise
+/// derived as `resume_from + Content-Length`.
+fn response_total_size(headers: &HeaderMap, resuming: bool, resume_from: u64) -> Option<u64> {
+    let content_range_total = headers
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_range_total.is_some() {
+        return content_range_total;
+    }
+
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())?;
+    Some(if resuming {
+        resume_from + content_length
+    } else {
+        content_length
+    })
+}
+
+/// Opens the partial file for writing: appending if `resuming`, truncating
+/// it fresh otherwise.
+fn open_partial(partial_path: &Path, resuming: bool) -> Result<File> {
+    if resuming {
+        Ok(OpenOptions::new().append(true).open(partial_path)?)
+    } else {
+        Ok(File::create(partial_path)?)
+    }
+}
+
+/// Streams `reader` into the already-open `file`, returning the total bytes
+/// now on disk (the existing prefix plus whatever `reader` yields).
+///
+/// `*hashed_len` records how many bytes of the on-disk `.partial` file
+/// `hasher` has already absorbed. Whenever that no longer matches
+/// `resume_from` (the file's actual size at the start of this attempt) —
+/// whether because this is the first time we're resuming a `.partial` left
+/// by an earlier attempt/process, or because a previous attempt's write
+/// landed more bytes on disk than it managed to hash before failing —
+/// `hasher` is rebuilt from scratch over the file's current contents (see
+/// [`rehash_partial`]) rather than trusted. This keeps the digest correct
+/// while still skipping that disk read on the common case of a retry that
+/// picks up exactly where the last one left off.
+#[allow(clippy::too_many_arguments)]
+fn stream_to_partial<R: Read>(
+    mut reader: R,
+    mut file: File,
+    partial_path: &Path,
+    hasher: &mut Sha256,
+    resuming: bool,
+    hashed_len: &mut u64,
+    resume_from: u64,
+    total_size: Option<u64>,
+) -> Result<u64> {
+    if !resuming {
+        *hasher = Sha256::new();
+        *hashed_len = 0;
+    } else if *hashed_len != resume_from {
+        *hasher = Sha256::new();
+        rehash_partial(partial_path, hasher)?;
+        *hashed_len = resume_from;
+    }
+    let mut downloaded = if resuming { resume_from } else { 0 };
+
+    let mut buf = [0u8; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buf[..read])?;
+        hasher.update(&buf[..read]);
+        *hashed_len += read as u64;
+        downloaded += read as u64;
+        match total_size {
+            Some(total) => debug!("{} Downloaded {}/{} bytes", emoji::DOWNLOAD, downloaded, total),
+            None => debug!("{} Downloaded {} bytes", emoji::DOWNLOAD, downloaded),
+        }
+    }
+
+    Ok(downloaded)
+}
+
+/// Rebuilds `hasher`'s state over the bytes already written to
+/// `partial_path`, so a resumed transfer's digest covers the whole file
+/// rather than just the bytes appended in this attempt.
+fn rehash_partial(partial_path: &Path, hasher: &mut Sha256) -> Result<()> {
+    let mut existing = File::open(partial_path)?;
+    let mut buf = [0u8; CHUNK_SIZE];
+    loop {
+        let read = existing.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(())
+}
+
+/// Parses a `file://` URL into a local filesystem path, letting
+/// `--toolchain-repo`/`--llvm-repo` (and their companion `.sha256` files)
+/// point at a directory on disk for fully offline/air-gapped installs.
+/// Returns `None` for anything else (`http://`/`https://` URLs).
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    // `file:///C:/mirror` leaves a leading slash in front of the drive
+    // letter; Windows treats `/C:/mirror` as rooted on the current drive,
+    // not `C:\mirror`, so that slash has to go before handing it to `Path`.
+    let path = path
+        .strip_prefix('/')
+        .filter(|rest| rest.as_bytes().get(1) == Some(&b':'))
+        .unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+/// Uncompresses `archive_path` into `output_directory`, dispatching on the
+/// archive's extension.
+fn uncompress_file(archive_path: &Path, output_directory: &str) -> Result<()> {
+    let file_name = archive_path.display().to_string();
+    if file_name.ends_with(".zip") {
+        let file = File::open(archive_path)?;
+        let mut archive = zip::ZipArchive::new(file)?;
+        archive.extract(output_directory)?;
+    } else if file_name.ends_with(".tar.xz") {
+        let file = File::open(archive_path)?;
+        let tar = xz2::read::XzDecoder::new(file);
+        let mut archive = tar::Archive::new(tar);
+        archive.unpack(output_directory)?;
+    } else {
+        bail!(
+            "{} Unsupported archive format for '{}'",
+            emoji::ERROR,
+            file_name
+        );
+    }
+    Ok(())
+}
+
+/// Best-effort fetch of the checksum file conventionally published
+/// alongside a release asset (`<asset>.sha256`, or, for a `file://` asset
+/// URL, a `.sha256` sibling on disk). Returns `None` rather than erroring
+/// out if no such file exists, since not every mirror publishes one, but
+/// always `warn!`s first so the degraded (no integrity check) path is
+/// visible instead of silently skipped.
+pub fn fetch_companion_checksum(asset_url: &str) -> Option<String> {
+    let checksum_url = format!("{asset_url}.sha256");
+
+    let body = if let Some(path) = file_url_path(&checksum_url) {
+        match fs::read_to_string(&path) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(
+                    "{} No companion checksum found at '{}' ({}), proceeding without integrity verification",
+                    emoji::WARN,
+                    path.display(),
+                    err
+                );
+                return None;
+            }
+        }
+    } else {
+        match Client::new().get(&checksum_url).send() {
+            Ok(response) if response.status().is_success() => match response.text() {
+                Ok(body) => body,
+                Err(err) => {
+                    warn!(
+                        "{} Failed to read companion checksum from '{}' ({}), proceeding without integrity verification",
+                        emoji::WARN,
+                        checksum_url,
+                        err
+                    );
+                    return None;
+                }
+            },
+            Ok(response) => {
+                warn!(
+                    "{} No companion checksum found at '{}' ({}), proceeding without integrity verification",
+                    emoji::WARN,
+                    checksum_url,
+                    response.status()
+                );
+                return None;
+            }
+            Err(err) => {
+                warn!(
+                    "{} Failed to fetch companion checksum from '{}' ({}), proceeding without integrity verification",
+                    emoji::WARN,
+                    checksum_url,
+                    err
+                );
+                return None;
+            }
+        }
+    };
+
+    body.split_whitespace().next().map(str::to_string)
+}
+
+/// Number of concurrent jobs to run by default: the available parallelism,
+/// falling back to a single job on platforms where it can't be determined.
+pub fn default_concurrency() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs `jobs` concurrently, bounded by `max_concurrency` in-flight jobs at
+/// once. Every job is run to completion even if a sibling fails, and their
+/// errors are aggregated so one failed download doesn't hide the others.
+pub fn run_concurrent<'a>(
+    jobs: Vec<Box<dyn FnOnce() -> Result<()> + Send + 'a>>,
+    max_concurrency: usize,
+) -> Result<()> {
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    let total_jobs = jobs.len();
+    let max_concurrency = max_concurrency.max(1).min(total_jobs);
+    let queue = Mutex::new(jobs);
+    let errors: Mutex<Vec<anyhow::Error>> = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..max_concurrency {
+            scope.spawn(|| loop {
+                let job = queue.lock().unwrap().pop();
+                let Some(job) = job else { break };
+                if let Err(err) = job() {
+                    errors.lock().unwrap().push(err);
+                }
+            });
+        }
+    });
+
+    let errors = errors.into_inner().unwrap();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let details = errors
+        .iter()
+        .map(|err| format!("- {err}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    bail!(
+        "{} {} of {} concurrent job(s) failed:\n{}",
+        emoji::ERROR,
+        errors.len(),
+        total_jobs,
+        details
+    )
+}
+
+/// Gets the path to the directory where a given tool's distribution
+/// artifacts are downloaded before being installed.
+pub fn get_dist_path(tool_name: &str) -> String {
+    let data_dir = dirs::data_local_dir().unwrap();
+    let dist_path = data_dir.join("espup").join("dist").join(tool_name);
+    dist_path.display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url_path_unix() {
+        assert_eq!(
+            file_url_path("file:///home/user/mirror/rust.tar.xz"),
+            Some(PathBuf::from("/home/user/mirror/rust.tar.xz"))
+        );
+    }
+
+    #[test]
+    fn test_file_url_path_windows_drive() {
+        assert_eq!(
+            file_url_path("file:///C:/mirror/rust.zip"),
+            Some(PathBuf::from("C:/mirror/rust.zip"))
+        );
+    }
+
+    #[test]
+    fn test_file_url_path_non_file_url() {
+        assert_eq!(
+            file_url_path("https://example.com/mirror/rust.tar.xz"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_response_total_size_from_content_range() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, "bytes 100-199/1000".parse().unwrap());
+        headers.insert(CONTENT_LENGTH, "100".parse().unwrap());
+        assert_eq!(response_total_size(&headers, true, 100), Some(1000));
+    }
+
+    #[test]
+    fn test_response_total_size_resuming_without_content_range() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, "900".parse().unwrap());
+        assert_eq!(response_total_size(&headers, true, 100), Some(1000));
+    }
+
+    #[test]
+    fn test_response_total_size_fresh_download() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, "1000".parse().unwrap());
+        assert_eq!(response_total_size(&headers, false, 0), Some(1000));
+    }
+
+    #[test]
+    fn test_response_total_size_unknown() {
+        let headers = HeaderMap::new();
+        assert_eq!(response_total_size(&headers, false, 0), None);
+    }
+}